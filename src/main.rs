@@ -8,26 +8,84 @@ use sdl2::keyboard::Scancode;
 use sdl2::pixels::Color;
 use sdl2::rect::Point;
 use sdl2::rect::Rect;
+use redis::Commands;
+use std::collections::BTreeMap;
 use std::collections::VecDeque;
+use std::net::ToSocketAddrs;
+use std::net::UdpSocket;
+use std::time::Duration;
+use std::time::Instant;
 
 #[derive(Debug)]
 struct AppOptions {
     debug: bool,
+    connect: Option<String>,
+    listen: Option<u16>,
+    level: LevelGeneratorConfig,
+    laser: Option<LaserConfig>,
 }
 
 fn get_app_options() -> AppOptions {
     let args: Vec<_> = std::env::args().skip(1).collect::<Vec<_>>();
     let have = |s: &str| args.contains(&s.to_string());
+    let value_of = |flag: &str| {
+        args.iter()
+            .position(|a| a == flag)
+            .and_then(|i| args.get(i + 1))
+            .cloned()
+    };
+    let float_value_of = |flag: &str, default: f64| {
+        value_of(flag).map_or(default, |v| {
+            v.parse()
+                .unwrap_or_else(|_| panic!("{} expects a number", flag))
+        })
+    };
 
     if have("-h") {
         println!(
             "Options:\n  \
-             -d  Debug (show events)"
+             -d                     Debug (show events)\n  \
+             --listen <port>        Host a two-player match on the given UDP port\n  \
+             --connect <addr>       Join a two-player match at host:port\n  \
+             --segment-spacing <n>  Tube segment spacing (default {})\n  \
+             --min-radius <n>       Minimum tube radius at maximum difficulty (default {})\n  \
+             --max-radius <n>       Maximum tube radius at the start (default {})\n  \
+             --volatility <n>       How sharply the corridor can bend (default {})\n  \
+             --laser-redis <url>    Also stream frames as laser points to this Redis server\n  \
+             --laser-channel <name> Redis channel to publish frames on (default {})\n  \
+             --laser-client <id>    Laser/client id tagged on each published frame (default {})\n  \
+             --laser-fps <n>        Laser publish rate in Hz (default {})",
+            LevelGeneratorConfig::default().segment_spacing,
+            LevelGeneratorConfig::default().min_radius,
+            LevelGeneratorConfig::default().max_radius,
+            LevelGeneratorConfig::default().volatility,
+            LaserConfig::DEFAULT_CHANNEL,
+            LaserConfig::DEFAULT_CLIENT_ID,
+            LaserConfig::DEFAULT_FRAME_RATE,
         );
         std::process::exit(0);
     }
 
-    AppOptions { debug: have("-d") }
+    let default = LevelGeneratorConfig::default();
+    AppOptions {
+        debug: have("-d"),
+        connect: value_of("--connect"),
+        listen: value_of("--listen").map(|p| p.parse().expect("--listen expects a port number")),
+        level: LevelGeneratorConfig {
+            segment_spacing: float_value_of("--segment-spacing", default.segment_spacing),
+            min_radius: float_value_of("--min-radius", default.min_radius),
+            max_radius: float_value_of("--max-radius", default.max_radius),
+            volatility: float_value_of("--volatility", default.volatility),
+        },
+        laser: value_of("--laser-redis").map(|redis_url| LaserConfig {
+            redis_url,
+            channel: value_of("--laser-channel")
+                .unwrap_or_else(|| LaserConfig::DEFAULT_CHANNEL.to_string()),
+            client_id: value_of("--laser-client")
+                .unwrap_or_else(|| LaserConfig::DEFAULT_CLIENT_ID.to_string()),
+            frame_rate: float_value_of("--laser-fps", LaserConfig::DEFAULT_FRAME_RATE),
+        }),
+    }
 }
 
 #[derive(Clone, Copy)]
@@ -43,22 +101,6 @@ impl<T> V2<T> {
 }
 
 impl V2<f64> {
-    fn normalized(self) -> Self {
-        let V2 { x, y } = self;
-        let norm = x * x + y * y;
-        V2 {
-            x: x / norm,
-            y: y / norm,
-        }
-    }
-
-    fn turn_left(self) -> Self {
-        V2 {
-            x: -self.y,
-            y: self.x,
-        }
-    }
-
     fn dot(self, other: Self) -> f64 {
         self.x * other.x + self.y * other.y
     }
@@ -86,40 +128,547 @@ impl std::ops::Sub for V2<f64> {
     }
 }
 
+#[derive(Clone)]
+struct Heli {
+    pos: V2<f64>,
+    vel: V2<f64>,
+    collided: bool,
+}
+
+impl Heli {
+    fn new() -> Self {
+        Heli {
+            pos: V2::new(0.1, 0.5),
+            vel: V2::new(0.0, 0.0),
+            collided: false,
+        }
+    }
+}
+
+/// Everything that feeds into a tick of simulation, kept deterministic so
+/// that two peers fed the same `seed` and the same `inputs` always land on
+/// the same state: no wall-clock timing or unseeded randomness here. Tube
+/// generation is owned by `generator`, which derives each segment from its
+/// own seed rather than an advancing `StdRng`, so a snapshot taken via
+/// `save_state` captures the whole simulation.
 struct AppState {
-    rng: StdRng,
+    seed: u64,
+    tick: u64,
     paused: bool,
-    collided: bool,
-    heli_pos: V2<f64>,
-    heli_vel: V2<f64>,
+    helis: [Heli; 2],
     tube: VecDeque<(V2<f64>, f64)>,
+    generator: LevelGenerator,
 }
 
-fn init_app_state(rng: StdRng) -> AppState {
+fn init_app_state(seed: u64, level_config: LevelGeneratorConfig) -> AppState {
     let tube: VecDeque<_> = [(V2::new(0.0, 0.5), 0.4), (V2::new(0.4, 0.5), 0.4)]
         .iter()
         .copied()
         .collect();
 
     let mut state = AppState {
-        rng,
+        seed,
+        tick: 0,
         paused: true,
-        collided: false,
-        heli_pos: V2::new(0.1, 0.5),
-        heli_vel: V2::new(0.0, 0.0),
+        helis: [Heli::new(), Heli::new()],
         tube,
+        generator: LevelGenerator::new(seed, level_config),
     };
     move_tube(&mut state);
     state
 }
 
+fn ground_points(tube: &VecDeque<(V2<f64>, f64)>) -> impl DoubleEndedIterator<Item = V2<f64>> + '_ {
+    tube.iter().map(|&(p, r)| p + V2::new(0.0, -r))
+}
+
+fn ceiling_points(tube: &VecDeque<(V2<f64>, f64)>) -> impl DoubleEndedIterator<Item = V2<f64>> + '_ {
+    tube.iter().map(|&(p, r)| p + V2::new(0.0, r))
+}
+
 impl AppState {
-    fn ground<'a>(&'a self) -> impl Iterator<Item = V2<f64>> + 'a {
-        self.tube.iter().map(|&(p, r)| p + V2::new(0.0, -r))
+    /// Serializes the whole deterministic simulation state for rollback.
+    fn save_state(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        buf.extend_from_slice(&self.seed.to_le_bytes());
+        buf.extend_from_slice(&self.tick.to_le_bytes());
+        buf.push(self.paused as u8);
+
+        for heli in &self.helis {
+            buf.extend_from_slice(&heli.pos.x.to_le_bytes());
+            buf.extend_from_slice(&heli.pos.y.to_le_bytes());
+            buf.extend_from_slice(&heli.vel.x.to_le_bytes());
+            buf.extend_from_slice(&heli.vel.y.to_le_bytes());
+            buf.push(heli.collided as u8);
+        }
+
+        buf.extend_from_slice(&(self.tube.len() as u64).to_le_bytes());
+        for &(p, r) in &self.tube {
+            buf.extend_from_slice(&p.x.to_le_bytes());
+            buf.extend_from_slice(&p.y.to_le_bytes());
+            buf.extend_from_slice(&r.to_le_bytes());
+        }
+
+        buf.extend_from_slice(&self.generator.next_segment.to_le_bytes());
+        buf.extend_from_slice(&self.generator.distance.to_le_bytes());
+        buf.extend_from_slice(&self.generator.current_center.to_le_bytes());
+        buf.extend_from_slice(&self.generator.target_center.to_le_bytes());
+
+        buf
     }
 
-    fn ceiling(&self) -> impl Iterator<Item = V2<f64>> + '_ {
-        self.tube.iter().map(|&(p, r)| p + V2::new(0.0, r))
+    fn load_state(&mut self, bytes: &[u8]) {
+        let mut cursor = ByteCursor::new(bytes);
+        self.seed = cursor.take_u64();
+        self.generator.seed = self.seed;
+        self.tick = cursor.take_u64();
+        self.paused = cursor.take_bool();
+
+        for heli in &mut self.helis {
+            heli.pos = V2::new(cursor.take_f64(), cursor.take_f64());
+            heli.vel = V2::new(cursor.take_f64(), cursor.take_f64());
+            heli.collided = cursor.take_bool();
+        }
+
+        let tube_len = cursor.take_u64();
+        self.tube = (0..tube_len)
+            .map(|_| {
+                let p = V2::new(cursor.take_f64(), cursor.take_f64());
+                let r = cursor.take_f64();
+                (p, r)
+            })
+            .collect();
+
+        self.generator.next_segment = cursor.take_u64();
+        self.generator.distance = cursor.take_f64();
+        self.generator.current_center = cursor.take_f64();
+        self.generator.target_center = cursor.take_f64();
+    }
+}
+
+/// Tiny reader over a byte slice used by `load_state` to read back what
+/// `save_state` wrote; keeps
+/// the (de)serialization free of an external crate dependency.
+struct ByteCursor<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> ByteCursor<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        ByteCursor { bytes, pos: 0 }
+    }
+
+    fn take_u64(&mut self) -> u64 {
+        let v = u64::from_le_bytes(self.bytes[self.pos..self.pos + 8].try_into().unwrap());
+        self.pos += 8;
+        v
+    }
+
+    fn take_f64(&mut self) -> f64 {
+        let v = f64::from_le_bytes(self.bytes[self.pos..self.pos + 8].try_into().unwrap());
+        self.pos += 8;
+        v
+    }
+
+    fn take_bool(&mut self) -> bool {
+        let v = self.bytes[self.pos] != 0;
+        self.pos += 1;
+        v
+    }
+}
+
+fn lerp(a: f64, b: f64, alpha: f64) -> f64 {
+    a + (b - a) * alpha
+}
+
+fn lerp_v2(a: V2<f64>, b: V2<f64>, alpha: f64) -> V2<f64> {
+    V2::new(lerp(a.x, b.x, alpha), lerp(a.y, b.y, alpha))
+}
+
+/// Builds the tube used for rendering by interpolating between the previous
+/// and current simulation tubes. Falls back to the current tube when a
+/// segment was recycled this frame and the two deques no longer line up.
+fn interpolated_tube(
+    prev_tube: &VecDeque<(V2<f64>, f64)>,
+    tube: &VecDeque<(V2<f64>, f64)>,
+    alpha: f64,
+) -> VecDeque<(V2<f64>, f64)> {
+    // Scrolling only moves segments left, so a front segment further right
+    // than before means one was dropped, even if another was added at the
+    // back in the same frame and the lengths still match.
+    let front_x = |t: &VecDeque<(V2<f64>, f64)>| t.front().map(|(p, _)| p.x);
+    if prev_tube.len() != tube.len() || front_x(tube) > front_x(prev_tube) {
+        return tube.clone();
+    }
+
+    prev_tube
+        .iter()
+        .zip(tube.iter())
+        .map(|(&(pa, ra), &(pb, rb))| (lerp_v2(pa, pb, alpha), lerp(ra, rb, alpha)))
+        .collect()
+}
+
+/// A two-player session over UDP, built around rollback: each side predicts
+/// the remote input as its latest one at or before that tick until the real
+/// one for that tick arrives, and resimulates from the last confirmed snapshot whenever a
+/// late input turns out to disagree with the prediction.
+struct NetSession {
+    socket: UdpSocket,
+    local_player: usize,
+    input_delay: u64,
+    local_inputs: BTreeMap<u64, bool>,
+    remote_inputs: BTreeMap<u64, bool>,
+    /// The remote input actually used to simulate each tick, so a later
+    /// confirmation can be compared against it to detect a misprediction.
+    used_remote_input: BTreeMap<u64, bool>,
+    snapshots: VecDeque<(u64, Vec<u8>)>,
+}
+
+const INPUT_DELAY: u64 = 2;
+const ROLLBACK_WINDOW: usize = 128;
+
+impl NetSession {
+    fn new(socket: UdpSocket, local_player: usize) -> Self {
+        // The handshake in `host`/`join` is the only blocking exchange; once the match
+        // is running we poll for input packets instead of waiting on them.
+        socket.set_nonblocking(true).ok();
+
+        NetSession {
+            socket,
+            local_player,
+            input_delay: INPUT_DELAY,
+            local_inputs: BTreeMap::new(),
+            remote_inputs: BTreeMap::new(),
+            used_remote_input: BTreeMap::new(),
+            snapshots: VecDeque::new(),
+        }
+    }
+
+    /// Hosts a match: waits for the first packet from a peer, then hands it
+    /// our freshly rolled seed so both sides generate an identical tube.
+    fn host(port: u16) -> Result<(Self, u64), String> {
+        let socket = UdpSocket::bind(("0.0.0.0", port)).map_err(|e| e.to_string())?;
+        let mut buf = [0u8; 1];
+        let (_, peer_addr) = socket.recv_from(&mut buf).map_err(|e| e.to_string())?;
+        socket.connect(peer_addr).map_err(|e| e.to_string())?;
+
+        let seed: u64 = StdRng::from_entropy().gen();
+        socket
+            .send(&seed.to_le_bytes())
+            .map_err(|e| e.to_string())?;
+
+        Ok((NetSession::new(socket, 0), seed))
+    }
+
+    /// Joins a hosted match and receives the shared seed from it.
+    fn join(addr: impl ToSocketAddrs) -> Result<(Self, u64), String> {
+        let socket = UdpSocket::bind(("0.0.0.0", 0)).map_err(|e| e.to_string())?;
+        socket.connect(addr).map_err(|e| e.to_string())?;
+        socket.send(&[0u8]).map_err(|e| e.to_string())?;
+
+        let mut buf = [0u8; 8];
+        socket.recv(&mut buf).map_err(|e| e.to_string())?;
+        let seed = u64::from_le_bytes(buf);
+
+        Ok((NetSession::new(socket, 1), seed))
+    }
+
+    fn remote_player(&self) -> usize {
+        1 - self.local_player
+    }
+
+    /// Samples and sends our input for `tick`, delayed by `input_delay` ticks
+    /// so it reaches the peer before it's due, and drains any inputs the
+    /// peer has sent us.
+    fn exchange_input(&mut self, tick: u64, input_up: bool) {
+        let send_tick = tick + self.input_delay;
+        self.local_inputs.insert(send_tick, input_up);
+        let mut packet = [0u8; 9];
+        packet[..8].copy_from_slice(&send_tick.to_le_bytes());
+        packet[8] = input_up as u8;
+        let _ = self.socket.send(&packet);
+
+        let mut buf = [0u8; 9];
+        while let Ok(n) = self.socket.recv(&mut buf) {
+            if n != 9 {
+                continue;
+            }
+            let remote_tick = u64::from_le_bytes(buf[..8].try_into().unwrap());
+            let remote_input = buf[8] != 0;
+            self.remote_inputs.insert(remote_tick, remote_input);
+        }
+    }
+
+    /// Best guess at the remote input for `tick`: the confirmed value if it
+    /// has arrived, otherwise a prediction from the latest confirmed input at
+    /// or before `tick`, defaulting to neutral if there is none yet. Predicting
+    /// from a tick *after* the one being simulated (e.g. the globally highest
+    /// confirmed tick) would have both peers disagree symmetrically on the
+    /// unconfirmable pre-`input_delay` ticks, permanently desyncing them since
+    /// those ticks are never confirmed and so never trigger a correcting
+    /// rollback.
+    fn remote_input(&self, tick: u64) -> bool {
+        self.remote_inputs
+            .range(..=tick)
+            .next_back()
+            .is_some_and(|(_, &input)| input)
+    }
+
+    /// Builds the input pair to simulate `tick` with, and records the
+    /// (possibly predicted) remote input so it can be checked later.
+    fn inputs_for_tick(&mut self, tick: u64) -> [bool; 2] {
+        let local = self.local_inputs.get(&tick).copied().unwrap_or(false);
+        let remote = self.remote_input(tick);
+        self.used_remote_input.insert(tick, remote);
+
+        let mut inputs = [false; 2];
+        inputs[self.local_player] = local;
+        inputs[self.remote_player()] = remote;
+        inputs
+    }
+
+    fn push_snapshot(&mut self, tick: u64, bytes: Vec<u8>) {
+        self.snapshots.push_back((tick, bytes));
+        while self.snapshots.len() > ROLLBACK_WINDOW {
+            self.snapshots.pop_front();
+        }
+
+        // We can never roll back further than the oldest retained snapshot,
+        // so anything before it is dead weight: drop it to keep these maps
+        // (and the per-tick scan in `mispredicted_tick`) bounded by
+        // `ROLLBACK_WINDOW` rather than growing for the whole match.
+        if let Some(&(horizon, _)) = self.snapshots.front() {
+            self.local_inputs.retain(|&t, _| t >= horizon);
+            self.remote_inputs.retain(|&t, _| t >= horizon);
+            self.used_remote_input.retain(|&t, _| t >= horizon);
+        }
+    }
+
+    /// Latest snapshot strictly before `tick`, to resimulate forward from.
+    fn snapshot_before(&self, tick: u64) -> Option<Vec<u8>> {
+        self.snapshots
+            .iter()
+            .rev()
+            .find(|(t, _)| *t < tick)
+            .map(|(_, bytes)| bytes.clone())
+    }
+
+    /// Earliest tick whose confirmed remote input disagrees with the one we
+    /// predicted and already simulated with, if any.
+    fn mispredicted_tick(&self) -> Option<u64> {
+        self.used_remote_input
+            .iter()
+            .find(|(tick, &used)| self.remote_inputs.get(tick).is_some_and(|&c| c != used))
+            .map(|(&tick, _)| tick)
+    }
+
+    fn forget_predictions_from(&mut self, tick: u64) {
+        self.used_remote_input.retain(|&t, _| t < tick);
+    }
+}
+
+/// The slice of simulation state a frame output backend needs: already
+/// interpolated for smooth motion between fixed-timestep ticks.
+struct FrameState<'a> {
+    helis: &'a [Heli],
+    tube: &'a VecDeque<(V2<f64>, f64)>,
+    /// Predicted path of the local heli under the currently held input, for
+    /// the debug "ghost" overlay. Empty when the overlay is off.
+    ghost: &'a [V2<f64>],
+}
+
+/// A frame output backend. The game loop calls `present` once per rendered
+/// frame regardless of target, so SDL and laser output (or both at once)
+/// stay in lockstep.
+trait FrameSink {
+    fn present(&mut self, frame: &FrameState);
+}
+
+struct SdlSink<'a> {
+    canvas: sdl2::render::WindowCanvas,
+    tex_heli: sdl2::render::Texture<'a>,
+    tex_explosion: sdl2::render::Texture<'a>,
+}
+
+impl<'a> FrameSink for SdlSink<'a> {
+    fn present(&mut self, frame: &FrameState) {
+        let canvas = &mut self.canvas;
+        canvas.set_draw_color(Color::RGB(0, 0, 0));
+        canvas.clear();
+
+        canvas.set_draw_color(Color::RGB(255, 255, 255));
+        let points: Vec<Point> = ground_points(frame.tube)
+            .map(|V2 { x, y }| Point::new((x * 1024.0) as i32, ((1.0 - y) * 640.0) as i32))
+            .collect();
+        canvas.draw_lines(&points[..]).expect("Rendering error");
+        let points: Vec<Point> = ceiling_points(frame.tube)
+            .map(|V2 { x, y }| Point::new((x * 1024.0) as i32, ((1.0 - y) * 640.0) as i32))
+            .collect();
+        canvas.draw_lines(&points[..]).expect("Rendering error");
+
+        for heli in frame.helis {
+            if heli.collided {
+                canvas.copy(
+                    &self.tex_explosion,
+                    None,
+                    Some(Rect::new(
+                        (heli.pos.x * 1024.0) as i32 - 32,
+                        ((1.0 - heli.pos.y) * 640.0) as i32 - 32,
+                        64,
+                        64,
+                    )),
+                )
+            } else {
+                canvas.copy(
+                    &self.tex_heli,
+                    None,
+                    Some(Rect::new(
+                        (heli.pos.x * 1024.0) as i32 - 32,
+                        ((1.0 - heli.pos.y) * 640.0) as i32 - 12,
+                        64,
+                        24,
+                    )),
+                )
+            }
+            .expect("Rendering error");
+        }
+
+        if !frame.ghost.is_empty() {
+            let to_point =
+                |p: V2<f64>| Point::new((p.x * 1024.0) as i32, ((1.0 - p.y) * 640.0) as i32);
+            let predicted_collision = frame.ghost.len() < PREDICT_STEPS;
+
+            for (i, pair) in frame.ghost.windows(2).enumerate() {
+                // Skip every other segment for a dashed look.
+                if i % 2 != 0 {
+                    continue;
+                }
+                let is_last_segment = i + 2 >= frame.ghost.len();
+                canvas.set_draw_color(if predicted_collision && is_last_segment {
+                    Color::RGB(255, 64, 64)
+                } else {
+                    Color::RGB(128, 128, 128)
+                });
+                canvas
+                    .draw_line(to_point(pair[0]), to_point(pair[1]))
+                    .expect("Rendering error");
+            }
+        }
+
+        canvas.present();
+    }
+}
+
+/// Configuration for streaming frames to a laser projector over Redis.
+#[derive(Debug, Clone)]
+struct LaserConfig {
+    redis_url: String,
+    channel: String,
+    client_id: String,
+    frame_rate: f64,
+}
+
+impl LaserConfig {
+    const DEFAULT_CHANNEL: &'static str = "rusty-navigator/frame";
+    const DEFAULT_CLIENT_ID: &'static str = "default";
+    const DEFAULT_FRAME_RATE: f64 = 30.0;
+}
+
+/// Streams each frame's line geometry to a Redis pub/sub channel as a
+/// normalized point list in `[-1, 1] x [-1, 1]` laser coordinates, at a
+/// fixed rate independent of the simulation/render rate.
+struct LaserSink {
+    conn: redis::Connection,
+    channel: String,
+    client_id: String,
+    frame_interval: Duration,
+    last_published: Option<Instant>,
+}
+
+impl LaserSink {
+    fn connect(config: &LaserConfig) -> Result<Self, String> {
+        let client = redis::Client::open(config.redis_url.as_str()).map_err(|e| e.to_string())?;
+        let conn = client.get_connection().map_err(|e| e.to_string())?;
+
+        Ok(LaserSink {
+            conn,
+            channel: config.channel.clone(),
+            client_id: config.client_id.clone(),
+            frame_interval: Duration::from_secs_f64(1.0 / config.frame_rate),
+            last_published: None,
+        })
+    }
+
+    /// Maps a point from the game's `[0, 1]`-ish world space to
+    /// `[-1, 1] x [-1, 1]` laser space, using the same up/down orientation
+    /// as the SDL view.
+    fn to_laser_space(p: V2<f64>) -> (f32, f32) {
+        (
+            (p.x * 2.0 - 1.0).clamp(-1.0, 1.0) as f32,
+            (1.0 - p.y * 2.0).clamp(-1.0, 1.0) as f32,
+        )
+    }
+
+    /// Builds the scan path for one frame: the ceiling polyline, then the
+    /// ground polyline walked back-to-front so it continues from where the
+    /// ceiling left off, then a small marker per heli. Ordering the two
+    /// polylines head-to-tail like this (instead of scanning each
+    /// independently) keeps the galvo from whipping across the work area
+    /// between them; consecutive duplicate points are dropped for the same
+    /// reason.
+    fn frame_points(frame: &FrameState) -> Vec<(f32, f32)> {
+        let mut points: Vec<(f32, f32)> = ceiling_points(frame.tube)
+            .map(LaserSink::to_laser_space)
+            .collect();
+        points.extend(ground_points(frame.tube).rev().map(LaserSink::to_laser_space));
+
+        const MARKER_RADIUS: f64 = 0.02;
+        for heli in frame.helis {
+            points.extend(
+                [
+                    heli.pos + V2::new(0.0, MARKER_RADIUS),
+                    heli.pos + V2::new(MARKER_RADIUS, 0.0),
+                    heli.pos + V2::new(0.0, -MARKER_RADIUS),
+                    heli.pos + V2::new(-MARKER_RADIUS, 0.0),
+                    heli.pos + V2::new(0.0, MARKER_RADIUS),
+                ]
+                .iter()
+                .map(|&p| LaserSink::to_laser_space(p)),
+            );
+        }
+
+        points.dedup();
+        points
+    }
+
+    fn encode_points(client_id: &str, points: &[(f32, f32)]) -> Vec<u8> {
+        let id_bytes = client_id.as_bytes();
+        let mut buf = Vec::with_capacity(4 + id_bytes.len() + 4 + points.len() * 8);
+        buf.extend_from_slice(&(id_bytes.len() as u32).to_le_bytes());
+        buf.extend_from_slice(id_bytes);
+        buf.extend_from_slice(&(points.len() as u32).to_le_bytes());
+        for &(x, y) in points {
+            buf.extend_from_slice(&x.to_le_bytes());
+            buf.extend_from_slice(&y.to_le_bytes());
+        }
+        buf
+    }
+}
+
+impl FrameSink for LaserSink {
+    fn present(&mut self, frame: &FrameState) {
+        if self
+            .last_published
+            .is_some_and(|t| t.elapsed() < self.frame_interval)
+        {
+            return;
+        }
+        self.last_published = Some(Instant::now());
+
+        let points = LaserSink::frame_points(frame);
+        let payload = LaserSink::encode_points(&self.client_id, &points);
+        let _: redis::RedisResult<()> = self.conn.publish(&self.channel, payload);
     }
 }
 
@@ -155,54 +704,39 @@ fn main() -> Result<(), String> {
         t
     };
 
-    let mut state = init_app_state(StdRng::seed_from_u64(0));
-
-    let mut render = |state: &AppState| {
-        canvas.set_draw_color(Color::RGB(0, 0, 0));
-        canvas.clear();
-
-        canvas.set_draw_color(Color::RGB(255, 255, 255));
-        let points: Vec<Point> = state
-            .ground()
-            .map(|V2 { x, y }| Point::new((x * 1024.0) as i32, ((1.0 - y) * 640.0) as i32))
-            .collect();
-        canvas.draw_lines(&points[..]).expect("Rendering error");
-        let points: Vec<Point> = state
-            .ceiling()
-            .map(|V2 { x, y }| Point::new((x * 1024.0) as i32, ((1.0 - y) * 640.0) as i32))
-            .collect();
-        canvas.draw_lines(&points[..]).expect("Rendering error");
-
-        if state.collided {
-            canvas.copy(
-                &tex_explosion,
-                None,
-                Some(Rect::new(
-                    (state.heli_pos.x * 1024.0) as i32 - 32,
-                    ((1.0 - state.heli_pos.y) * 640.0) as i32 - 32,
-                    64,
-                    64,
-                )),
-            )
-        } else {
-            canvas.copy(
-                &tex_heli,
-                None,
-                Some(Rect::new(
-                    (state.heli_pos.x * 1024.0) as i32 - 32,
-                    ((1.0 - state.heli_pos.y) * 640.0) as i32 - 12,
-                    64,
-                    24,
-                )),
-            )
+    let (mut net, seed) = match (opts.listen, &opts.connect) {
+        (Some(port), _) => {
+            let (net, seed) = NetSession::host(port)?;
+            (Some(net), seed)
+        }
+        (None, Some(addr)) => {
+            let (net, seed) = NetSession::join(addr.as_str())?;
+            (Some(net), seed)
         }
-        .expect("Rendering error");
+        (None, None) => (None, 0),
+    };
 
-        canvas.present();
+    let mut state = init_app_state(seed, opts.level);
+
+    let mut sdl_sink = SdlSink {
+        canvas,
+        tex_heli,
+        tex_explosion,
     };
+    let mut laser_sink = opts.laser.as_ref().map(LaserSink::connect).transpose()?;
+    let mut sinks: Vec<&mut dyn FrameSink> = vec![&mut sdl_sink];
+    if let Some(laser_sink) = laser_sink.as_mut() {
+        sinks.push(laser_sink);
+    }
+
+    const DT: f64 = 1.0 / 60.0;
+    let mut accumulator = 0.0;
+    let mut last_instant = std::time::Instant::now();
 
     'running: loop {
-        render(&state);
+        let now = std::time::Instant::now();
+        accumulator += (now - last_instant).as_secs_f64();
+        last_instant = now;
 
         for ev in event_pump.poll_iter() {
             if opts.debug {
@@ -226,7 +760,10 @@ fn main() -> Result<(), String> {
                     keycode: Some(Keycode::R),
                     repeat: false,
                     ..
-                } => state = init_app_state(state.rng),
+                } if net.is_none() => {
+                    state = init_app_state(StdRng::from_entropy().gen(), opts.level);
+                    accumulator = 0.0;
+                }
 
                 _ => {}
             }
@@ -236,32 +773,117 @@ fn main() -> Result<(), String> {
         let input_up = keystate.is_scancode_pressed(Scancode::Up)
             || keystate.is_scancode_pressed(Scancode::Space);
 
-        if input_up {
-            state.paused = false;
-        }
+        let prev_helis = state.helis.clone();
+        let prev_tube = state.tube.clone();
+
+        while accumulator >= DT {
+            accumulator -= DT;
+
+            let inputs = if let Some(net) = net.as_mut() {
+                net.exchange_input(state.tick, input_up);
 
-        if !state.collided && !state.paused {
-            state.heli_pos = state.heli_pos + state.heli_vel;
+                // A late remote input can reveal that we predicted wrong for
+                // a tick we already simulated; rewind to the snapshot from
+                // just before it and resimulate up to the present tick.
+                if let Some(bad_tick) = net.mispredicted_tick() {
+                    let resim_to = state.tick;
+                    if let Some(bytes) = net.snapshot_before(bad_tick) {
+                        state.load_state(&bytes);
+                        net.forget_predictions_from(bad_tick);
+                        while state.tick < resim_to {
+                            let resim_inputs = net.inputs_for_tick(state.tick);
+                            advance(&mut state, resim_inputs);
+                            net.push_snapshot(state.tick, state.save_state());
+                        }
+                    }
+                }
 
-            if input_up {
-                state.heli_vel.y += 0.0001;
+                net.inputs_for_tick(state.tick)
             } else {
-                state.heli_vel.y -= 0.0001;
-            }
+                [input_up, false]
+            };
 
-            move_tube(&mut state);
+            advance(&mut state, inputs);
 
-            state.collided = is_collided(&state);
+            if let Some(net) = net.as_mut() {
+                net.push_snapshot(state.tick, state.save_state());
+            }
         }
 
-        std::thread::sleep(std::time::Duration::from_millis(20));
+        let alpha = accumulator / DT;
+        let render_helis = [
+            Heli {
+                pos: lerp_v2(prev_helis[0].pos, state.helis[0].pos, alpha),
+                vel: state.helis[0].vel,
+                collided: state.helis[0].collided,
+            },
+            Heli {
+                pos: lerp_v2(prev_helis[1].pos, state.helis[1].pos, alpha),
+                vel: state.helis[1].vel,
+                collided: state.helis[1].collided,
+            },
+        ];
+        let render_tube = interpolated_tube(&prev_tube, &state.tube, alpha);
+        let visible_helis = if net.is_some() {
+            &render_helis[..]
+        } else {
+            &render_helis[..1]
+        };
+
+        let local_player = net.as_ref().map_or(0, |n| n.local_player);
+        let ghost = if opts.debug {
+            predict(&state, local_player, input_up, PREDICT_STEPS)
+        } else {
+            Vec::new()
+        };
+
+        let frame = FrameState {
+            helis: visible_helis,
+            tube: &render_tube,
+            ghost: &ghost,
+        };
+        for sink in sinks.iter_mut() {
+            sink.present(&frame);
+        }
     }
 
     Ok(())
 }
 
+fn advance(state: &mut AppState, inputs: [bool; 2]) {
+    // Unpausing off the synchronized `inputs` (rather than a peer's raw,
+    // locally-timed input) keeps it deterministic: both sides see the same
+    // inputs for a given tick, so both leave `paused` the same way on the
+    // same tick, and a misprediction here gets corrected by rollback just
+    // like any other state in the snapshot.
+    if inputs[0] || inputs[1] {
+        state.paused = false;
+    }
+
+    if state.paused {
+        return;
+    }
+
+    state.tick += 1;
+    move_tube(state);
+
+    for (heli, input_up) in state.helis.iter_mut().zip(inputs.iter().copied()) {
+        if heli.collided {
+            continue;
+        }
+
+        heli.pos = heli.pos + heli.vel;
+        heli.vel.y += if input_up { 0.0001 } else { -0.0001 };
+    }
+
+    for heli in state.helis.iter_mut() {
+        if !heli.collided {
+            heli.collided = is_collided(&state.tube, heli.pos);
+        }
+    }
+}
+
 fn move_tube(state: &mut AppState) {
-    let rng = &mut state.rng;
     let tube = &mut state.tube;
 
     for (p, _) in tube.iter_mut() {
@@ -273,59 +895,213 @@ fn move_tube(state: &mut AppState) {
     }
 
     while tube.back().filter(|(p, _)| p.x >= 1.0).is_none() {
-        let new_x = tube.back().map_or(0.0, |(p, _)| p.x + 1.0 / 5.0);
-        tube.push_back((
-            V2::new(new_x, rng.gen_range(0.2, 0.8)),
-            rng.gen_range(0.1, 0.2),
-        ));
+        let last_x = tube.back().map_or(0.0, |(p, _)| p.x);
+        tube.push_back(state.generator.next_segment(last_x));
+    }
+}
+
+/// Tunables for `LevelGenerator`, exposed on the command line so a run's
+/// difficulty ramp is reproducible.
+#[derive(Debug, Clone, Copy)]
+struct LevelGeneratorConfig {
+    segment_spacing: f64,
+    min_radius: f64,
+    max_radius: f64,
+    volatility: f64,
+}
+
+impl Default for LevelGeneratorConfig {
+    fn default() -> Self {
+        LevelGeneratorConfig {
+            segment_spacing: 1.0 / 5.0,
+            min_radius: 0.07,
+            max_radius: 0.2,
+            volatility: 0.05,
+        }
+    }
+}
+
+/// Owns the tube's spawning policy: it tracks total scroll distance and
+/// narrows the radius range and raises the corridor's volatility as that
+/// distance grows, while interpolating successive centers so the corridor
+/// meanders along a smooth curve instead of jumping between independent
+/// random points. Seeded independently of the rest of the simulation so the
+/// difficulty ramp can be reasoned about (and tested) on its own.
+///
+/// `next_segment`/`distance`/`current_center`/`target_center` are the only
+/// fields that change during play, so those are what `AppState::save_state`
+/// snapshots for rollback; `config` stays constant for the run.
+struct LevelGenerator {
+    seed: u64,
+    config: LevelGeneratorConfig,
+    next_segment: u64,
+    distance: f64,
+    current_center: f64,
+    target_center: f64,
+}
+
+/// Distance over which the difficulty ramp goes from 0 to fully ramped.
+const DIFFICULTY_RAMP_DISTANCE: f64 = 10.0;
+/// How much of the gap to `target_center` closes per spawned segment.
+const CENTER_STEP_FRACTION: f64 = 0.15;
+
+impl LevelGenerator {
+    fn new(seed: u64, config: LevelGeneratorConfig) -> Self {
+        LevelGenerator {
+            seed,
+            config,
+            next_segment: 0,
+            distance: 0.0,
+            current_center: 0.5,
+            target_center: 0.5,
+        }
+    }
+
+    /// 0.0 at the start of a run, ramping up to 1.0 by `DIFFICULTY_RAMP_DISTANCE`.
+    fn difficulty(&self) -> f64 {
+        (self.distance / DIFFICULTY_RAMP_DISTANCE).min(1.0)
+    }
+
+    fn next_segment(&mut self, last_x: f64) -> (V2<f64>, f64) {
+        let mut rng = StdRng::seed_from_u64(self.seed ^ self.next_segment);
+        self.next_segment += 1;
+
+        let difficulty = self.difficulty();
+        let radius_ceiling = (lerp(self.config.max_radius, self.config.min_radius, difficulty))
+            .max(self.config.min_radius + 1e-3);
+        let radius = rng.gen_range(self.config.min_radius, radius_ceiling);
+
+        self.current_center += (self.target_center - self.current_center) * CENTER_STEP_FRACTION;
+        if (self.target_center - self.current_center).abs() < 0.01 {
+            let volatility = self.config.volatility * (1.0 + difficulty * 3.0);
+            let margin = radius_ceiling;
+            let low = (self.current_center - volatility).max(margin);
+            let high = (self.current_center + volatility).min(1.0 - margin).max(low + 1e-3);
+            self.target_center = rng.gen_range(low, high);
+        }
+
+        self.distance += self.config.segment_spacing;
+
+        (
+            V2::new(last_x + self.config.segment_spacing, self.current_center),
+            radius,
+        )
     }
 }
 
-fn segment_point_distance((seg_start, seg_end): (V2<f64>, V2<f64>), point: V2<f64>) -> f64 {
-    (seg_end - seg_start)
-        .turn_left()
-        .normalized()
-        .dot(point - seg_start)
+fn closest_point_on_segment(a: V2<f64>, b: V2<f64>, p: V2<f64>) -> V2<f64> {
+    let ab = b - a;
+    let t = ((p - a).dot(ab) / ab.dot(ab)).clamp(0.0, 1.0);
+    a + V2::new(ab.x * t, ab.y * t)
 }
 
-fn is_collided(state: &AppState) -> bool {
+fn is_collided(tube: &VecDeque<(V2<f64>, f64)>, pos: V2<f64>) -> bool {
     const HELI_RADIUS: f64 = 0.03;
-    fn between((start, end): (f64, f64), x: f64) -> bool {
-        x > start && x < end
+
+    fn hits_wall(wall: impl Iterator<Item = V2<f64>>, pos: V2<f64>) -> bool {
+        let points: Vec<V2<f64>> = wall.collect();
+        points.windows(2).any(|pair| {
+            let closest = closest_point_on_segment(pair[0], pair[1], pos);
+            (pos - closest).dot(pos - closest).sqrt() < HELI_RADIUS
+        })
     }
-    fn max(x: f64, y: f64) -> f64 {
-        if x > y {
-            x
-        } else {
-            y
+
+    hits_wall(ground_points(tube), pos) || hits_wall(ceiling_points(tube), pos)
+}
+
+/// Ticks of lookahead the "ghost" overlay simulates, at the fixed 60 Hz
+/// timestep this is about one second.
+const PREDICT_STEPS: usize = 60;
+
+/// Simulates the `local_player` heli's flight for up to `steps` ticks under
+/// `input_up`, using the same integration and gravity as `advance`, but
+/// against a frozen copy of the tube rather than a scrolling one. Stops
+/// early at the first point where the real collision test reports a hit, so
+/// a returned path shorter than `steps` ends at a predicted collision.
+fn predict(state: &AppState, local_player: usize, input_up: bool, steps: usize) -> Vec<V2<f64>> {
+    let mut pos = state.helis[local_player].pos;
+    let mut vel = state.helis[local_player].vel;
+    let mut path = Vec::with_capacity(steps);
+
+    for _ in 0..steps {
+        pos = pos + vel;
+        vel.y += if input_up { 0.0001 } else { -0.0001 };
+        path.push(pos);
+
+        if is_collided(&state.tube, pos) {
+            break;
         }
     }
-    fn min(x: f64, y: f64) -> f64 {
-        if x < y {
-            x
-        } else {
-            y
+
+    path
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn save_state_load_state_round_trips() {
+        let mut state = init_app_state(42, LevelGeneratorConfig::default());
+        for _ in 0..30 {
+            advance(&mut state, [true, false]);
         }
+        let bytes = state.save_state();
+
+        let mut reloaded = init_app_state(0, LevelGeneratorConfig::default());
+        reloaded.load_state(&bytes);
+
+        assert_eq!(reloaded.save_state(), bytes);
+    }
+
+    #[test]
+    fn advance_is_deterministic_given_the_same_inputs() {
+        let config = LevelGeneratorConfig::default();
+        let mut a = init_app_state(1234, config);
+        let mut b = init_app_state(1234, config);
+
+        let inputs = [
+            [true, false],
+            [true, true],
+            [false, false],
+            [false, true],
+            [true, false],
+        ];
+        for _ in 0..20 {
+            for &step in &inputs {
+                advance(&mut a, step);
+                advance(&mut b, step);
+            }
+        }
+
+        assert_eq!(a.save_state(), b.save_state());
     }
 
-    // TODO: Do proper circle-polygon intersection
-    let pos = state.heli_pos;
-    let hit_ground = state
-        .ground()
-        .zip(state.ground().skip(1))
-        .any(|(start, end)| {
-            between((start.x, end.x), pos.x)
-                && pos.y - HELI_RADIUS < max(start.y, end.y)
-                && segment_point_distance((start, end), pos) < HELI_RADIUS
-        });
-    let hit_ceiling = state
-        .ceiling()
-        .zip(state.ceiling().skip(1))
-        .any(|(start, end)| {
-            between((start.x, end.x), pos.x)
-                && pos.y + HELI_RADIUS > min(start.y, end.y)
-                && segment_point_distance((end, start), pos) < HELI_RADIUS
-        });
-
-    hit_ground || hit_ceiling
+    #[test]
+    fn level_generator_next_segment_is_deterministic() {
+        let config = LevelGeneratorConfig::default();
+        let mut a = LevelGenerator::new(99, config);
+        let mut b = LevelGenerator::new(99, config);
+
+        let mut last_x = 0.0;
+        for _ in 0..50 {
+            let (pa, ra) = a.next_segment(last_x);
+            let (pb, rb) = b.next_segment(last_x);
+            assert_eq!((pa.x, pa.y, ra), (pb.x, pb.y, rb));
+            last_x = pa.x;
+        }
+    }
+
+    #[test]
+    fn level_generator_keeps_radius_within_configured_bounds() {
+        let config = LevelGeneratorConfig::default();
+        let mut generator = LevelGenerator::new(7, config);
+
+        let mut last_x = 0.0;
+        for _ in 0..200 {
+            let (p, radius) = generator.next_segment(last_x);
+            assert!(radius >= config.min_radius && radius <= config.max_radius);
+            last_x = p.x;
+        }
+    }
 }